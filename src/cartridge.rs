@@ -10,6 +10,19 @@ pub type InputPins = [InputPin; 8];
 pub type OutputPins = [OutputPin; 8];
 pub type OutputBuffer = [u8; 512];
 
+/// Unlock-sequence addresses shared by the AM29F/MX29-style command set.
+const FLASH_UNLOCK_ADDR_1: u16 = 0xAAA;
+const FLASH_UNLOCK_ADDR_2: u16 = 0x555;
+
+/// MBC3 RTC register select values, written to the RAM-bank select register.
+const RTC_SECONDS: u8 = 0x08;
+const RTC_DAY_HIGH: u8 = 0x0C;
+
+/// Halt bit within the day-high RTC register.
+const RTC_HALT_BIT: u8 = 0x40;
+
+pub type RtcRegisters = [u8; 5];
+
 pub struct CartridgeConnection {
     pub address_in: ShiftRegister,
     pub read_pin: Pin<mode::Output>,
@@ -18,6 +31,22 @@ pub struct CartridgeConnection {
     pub output_pins: Option<OutputPins>,
     pub header: Option<CartridgeHeader>,
     pub mbc: MemoryBankController,
+    pub timing: BusTiming,
+}
+
+/// Runtime-adjustable bus timing, applied by `read_byte`/`write_byte`.
+pub struct BusTiming {
+    pub read_settle_us: u16,
+    pub write_pulse_ms: u16,
+}
+
+impl Default for BusTiming {
+    fn default() -> Self {
+        Self {
+            read_settle_us: 0,
+            write_pulse_ms: 2,
+        }
+    }
 }
 
 pub enum MemoryBankController {
@@ -105,6 +134,7 @@ impl CartridgeConnection {
             output_pins: None,
             header: None,
             mbc: MemoryBankController::RomOnly,
+            timing: BusTiming::default(),
         };
         let header = CartridgeHeader::from_cartridge_connection(&mut ret);
         ret.mbc = MemoryBankController::from_cartridge_header(&header);
@@ -159,6 +189,90 @@ impl CartridgeConnection {
         self.write_byte(0, 0);
     }
 
+    /// Writes the unlock prefix (`0xAA`/`0x55`) that precedes every flash command.
+    fn flash_unlock(&mut self) {
+        self.write_byte(FLASH_UNLOCK_ADDR_1, 0xAA);
+        self.write_byte(FLASH_UNLOCK_ADDR_2, 0x55);
+    }
+
+    /// Sends the reset command, returning the flash chip to normal read mode.
+    pub fn flash_reset(&mut self) {
+        self.write_byte(0, 0xF0);
+    }
+
+    /// Polls via DQ7 until bit 7 of the byte at `address` matches bit 7 of `expected`.
+    fn flash_poll(&mut self, address: u16, expected: u8) {
+        let expected_bit7 = expected & 0x80;
+        while self.read_byte(address) & 0x80 != expected_bit7 {}
+    }
+
+    /// Programs a single byte at `address` in `bank`.
+    pub fn program_flash_byte(&mut self, bank: u16, address: u16, value: u8) {
+        if bank != 0 {
+            self.select_rom_bank(bank);
+        }
+        self.flash_unlock();
+        self.write_byte(FLASH_UNLOCK_ADDR_1, 0xA0);
+        self.write_byte(address, value);
+        self.flash_poll(address, value);
+    }
+
+    /// Erases the flash sector starting at `sector_base` within `bank`.
+    pub fn erase_flash_sector(&mut self, bank: u16, sector_base: u16) {
+        if bank != 0 {
+            self.select_rom_bank(bank);
+        }
+        self.flash_unlock();
+        self.write_byte(FLASH_UNLOCK_ADDR_1, 0x80);
+        self.flash_unlock();
+        self.write_byte(sector_base, 0x30);
+        self.flash_poll(sector_base, 0xFF);
+    }
+
+    /// Issues the autoselect command and returns (manufacturer_id, device_id).
+    pub fn read_flash_id(&mut self) -> (u8, u8) {
+        self.flash_unlock();
+        self.write_byte(FLASH_UNLOCK_ADDR_1, 0x90);
+        let manufacturer_id = self.read_byte(0x0000);
+        let device_id = self.read_byte(0x0001);
+        self.flash_reset();
+        (manufacturer_id, device_id)
+    }
+
+    /// Selects an RTC register through the MBC3 RAM-bank select register.
+    fn select_rtc_register(&mut self, register: u8) {
+        self.write_byte(0x4000, register);
+    }
+
+    /// Copies the running RTC counters into the latch registers.
+    fn latch_rtc(&mut self) {
+        self.write_byte(0x6000, 0x00);
+        self.write_byte(0x6000, 0x01);
+    }
+
+    /// Latches and reads the five MBC3 RTC registers.
+    pub fn read_rtc_registers(&mut self) -> RtcRegisters {
+        self.latch_rtc();
+        let mut values = [0u8; 5];
+        for (i, v) in values.iter_mut().enumerate() {
+            self.select_rtc_register(RTC_SECONDS + i as u8);
+            *v = self.read_byte(0xA000);
+        }
+        values
+    }
+
+    /// Halts the clock and restores the five MBC3 RTC registers.
+    pub fn write_rtc_registers(&mut self, values: &RtcRegisters) {
+        self.select_rtc_register(RTC_DAY_HIGH);
+        let day_high = self.read_byte(0xA000);
+        self.write_byte(0xA000, day_high | RTC_HALT_BIT);
+
+        for (i, v) in values.iter().enumerate() {
+            self.select_rtc_register(RTC_SECONDS + i as u8);
+            self.write_byte(0xA000, *v);
+        }
+    }
+
     pub fn read_block(&mut self, address: u16) -> OutputBuffer {
         let mut bytes = [0u8; 512];
         let mut address = address;
@@ -183,6 +297,9 @@ impl CartridgeConnection {
         self.write_pin.set_high();
         self.read_pin.set_low();
         self.set_address(address);
+        if self.timing.read_settle_us > 0 {
+            arduino_hal::delay_us(self.timing.read_settle_us as u32);
+        }
         let value = data_pins_to_byte(self.input_pins.as_ref().unwrap());
         self.read_pin.set_high();
         value
@@ -201,12 +318,12 @@ impl CartridgeConnection {
             }
         }
 
-        arduino_hal::delay_ms(2);
+        arduino_hal::delay_ms(self.timing.write_pulse_ms);
 
         self.read_pin.set_high();
         self.write_pin.set_low();
 
-        arduino_hal::delay_ms(2);
+        arduino_hal::delay_ms(self.timing.write_pulse_ms);
 
         self.write_pin.set_high();
 
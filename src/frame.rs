@@ -0,0 +1,22 @@
+/// CRC-16/CCITT-FALSE (poly `0x1021`, init `0xFFFF`) used to protect serial frames.
+pub fn crc16_ccitt(data: &[u8]) -> u16 {
+    crc16_ccitt_update(0xFFFF, data)
+}
+
+/// Continues a CRC-16/CCITT-FALSE computation from a prior `crc` value, so a
+/// frame header and payload can be checksummed together without copying them
+/// into one buffer first.
+pub fn crc16_ccitt_update(crc: u16, data: &[u8]) -> u16 {
+    let mut crc = crc;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            if crc & 0x8000 != 0 {
+                crc = (crc << 1) ^ 0x1021;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
@@ -3,18 +3,29 @@
 use panic_halt as _;
 
 mod cartridge;
+mod frame;
 mod shift;
 use arduino_hal::pac::USART0;
 use arduino_hal::port::{mode, Pin};
-use cartridge::CartridgeConnection;
+use cartridge::{BusTiming, CartridgeConnection};
+use frame::{crc16_ccitt, crc16_ccitt_update};
 use shift::ShiftRegister;
 
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+
 enum Command {
     NoOp,
     DumpHeader,
     DumpRom,
     DumpRam,
     FlashRam,
+    FlashRom,
+    IdentifyFlash,
+    VerifyChecksums,
+    DumpRtc,
+    FlashRtc,
+    Configure,
 }
 
 impl Command {
@@ -24,11 +35,24 @@ impl Command {
             1 => Command::DumpRom,
             2 => Command::DumpRam,
             4 => Command::FlashRam,
+            5 => Command::FlashRom,
+            6 => Command::IdentifyFlash,
+            7 => Command::VerifyChecksums,
+            8 => Command::DumpRtc,
+            9 => Command::FlashRtc,
+            10 => Command::Configure,
             _ => Command::NoOp,
         }
     }
 }
 
+/// Reprograms the USART0 baud-rate divisor in place, always clearing U2X0 first.
+fn set_baud_rate_divisor(ubrr: u16) {
+    let usart = unsafe { &*USART0::ptr() };
+    usart.ucsr0a.modify(|_, w| w.u2x0().clear_bit());
+    usart.ubrr0.write(|w| unsafe { w.bits(ubrr) });
+}
+
 type Serial = arduino_hal::hal::usart::Usart<
     USART0,
     Pin<mode::Input, arduino_hal::hal::port::PD0>,
@@ -36,7 +60,62 @@ type Serial = arduino_hal::hal::usart::Usart<
     arduino_hal::clock::MHz16,
 >;
 
-fn dump_rom_bank(bank: u16, cartridge: &mut CartridgeConnection, serial: &mut Serial) {
+/// Sends `payload` as a frame (seq, length, payload, CRC-16/CCITT over the
+/// header and payload) and waits for the host to ACK it, resending on NAK.
+fn send_framed(seq: u8, payload: &[u8], serial: &mut Serial) {
+    let len = payload.len() as u16;
+    let header = [seq, (len >> 8) as u8, len as u8];
+    loop {
+        for b in header {
+            serial.write_byte(b);
+        }
+        for b in payload {
+            serial.write_byte(*b);
+        }
+        let crc = crc16_ccitt_update(crc16_ccitt(&header), payload);
+        serial.write_byte((crc >> 8) as u8);
+        serial.write_byte(crc as u8);
+
+        if serial.read_byte() == ACK {
+            return;
+        }
+    }
+}
+
+/// Receives a frame (seq, length, payload, CRC-16/CCITT over the header and
+/// payload) into `payload`, NAKing and waiting for a resend until the
+/// declared length matches `payload.len()` and the CRC checks out. The
+/// payload is always read at its fixed protocol size (32/512 bytes) rather
+/// than the frame's own length field, so a corrupted length byte can't make
+/// this under-read and leave stale bytes in `payload`. Returns the frame's
+/// sequence number.
+fn recv_framed(payload: &mut [u8], serial: &mut Serial) -> u8 {
+    loop {
+        let seq = serial.read_byte();
+        let len_hi = serial.read_byte();
+        let len_lo = serial.read_byte();
+        let len = ((len_hi as usize) << 8) | len_lo as usize;
+
+        for b in payload.iter_mut() {
+            *b = serial.read_byte();
+        }
+
+        let crc_hi = serial.read_byte() as u16;
+        let crc_lo = serial.read_byte() as u16;
+        let received_crc = (crc_hi << 8) | crc_lo;
+
+        let header = [seq, len_hi, len_lo];
+        let expected_crc = crc16_ccitt_update(crc16_ccitt(&header), payload);
+
+        if len == payload.len() && expected_crc == received_crc {
+            serial.write_byte(ACK);
+            return seq;
+        }
+        serial.write_byte(NAK);
+    }
+}
+
+fn dump_rom_bank(bank: u16, cartridge: &mut CartridgeConnection, serial: &mut Serial, seq: &mut u8) {
     let mut address_base = 0;
     if bank != 0 {
         address_base = 0x4000;
@@ -44,20 +123,18 @@ fn dump_rom_bank(bank: u16, cartridge: &mut CartridgeConnection, serial: &mut Se
     }
     for i in 0..32 {
         let buffer = cartridge.read_block(address_base + i * 512);
-        for b in buffer {
-            serial.write_byte(b);
-        }
+        send_framed(*seq, &buffer, serial);
+        *seq = seq.wrapping_add(1);
     }
 }
 
-fn dump_ram_bank(bank: u8, cartridge: &mut CartridgeConnection, serial: &mut Serial) {
+fn dump_ram_bank(bank: u8, cartridge: &mut CartridgeConnection, serial: &mut Serial, seq: &mut u8) {
     let address_base = 0xA000;
     cartridge.select_ram_bank(bank);
     for i in 0..16 {
         let buffer = cartridge.read_block(address_base + i * 512);
-        for b in buffer {
-            serial.write_byte(b);
-        }
+        send_framed(*seq, &buffer, serial);
+        *seq = seq.wrapping_add(1);
     }
 }
 
@@ -65,19 +142,71 @@ fn flash_ram_bank(bank: u8, cartridge: &mut CartridgeConnection, serial: &mut Se
     cartridge.select_ram_bank(bank);
     for i in 0..256 {
         let mut buffer = [0u8; 32];
-        for b in &mut buffer {
-            *b = serial.read_byte();
-        }
+        recv_framed(&mut buffer, serial);
         for (j, b) in buffer.iter().enumerate() {
             cartridge.write_byte(0xA000 + i * 32 + j as u16, *b);
         }
-        // send end of chunk
-        serial.write_byte(0xAB);
     }
     // send end of bank
     serial.write_byte(0xAA);
 }
 
+fn flash_rom_bank(bank: u16, cartridge: &mut CartridgeConnection, serial: &mut Serial) {
+    let address_base = if bank == 0 { 0 } else { 0x4000 };
+    cartridge.erase_flash_sector(bank, address_base);
+    for i in 0..512 {
+        let mut buffer = [0u8; 32];
+        recv_framed(&mut buffer, serial);
+        for (j, b) in buffer.iter().enumerate() {
+            cartridge.program_flash_byte(bank, address_base + i * 32 + j as u16, *b);
+        }
+    }
+    // send end of bank
+    serial.write_byte(0xAA);
+}
+
+fn verify_checksums(cartridge: &mut CartridgeConnection, serial: &mut Serial) {
+    let mut num_banks = 0;
+    if let Some(header) = cartridge.header.as_ref() {
+        num_banks = header.decode_rom_size();
+    }
+
+    let mut header_checksum: u8 = 0;
+    let mut global_checksum: u16 = 0;
+    for bank in 0..num_banks {
+        let address_base = if bank == 0 { 0 } else { 0x4000 };
+        if bank != 0 {
+            cartridge.select_rom_bank(bank);
+        }
+        for i in 0..32 {
+            let buffer = cartridge.read_block(address_base + i * 512);
+            for (j, b) in buffer.iter().enumerate() {
+                let address = address_base + i * 512 + j as u16;
+                if bank == 0 && (0x134..=0x14C).contains(&address) {
+                    header_checksum = header_checksum.wrapping_sub(*b).wrapping_sub(1);
+                }
+                if !(bank == 0 && (0x14E..=0x14F).contains(&address)) {
+                    global_checksum = global_checksum.wrapping_add(*b as u16);
+                }
+            }
+        }
+    }
+
+    // header.global_checksum is read off the big-endian in-ROM bytes via a
+    // packed struct on a little-endian target, so it comes out byte-swapped
+    // relative to the spec value the accumulator above produces.
+    let (expected_header, expected_global) = match cartridge.header.as_ref() {
+        Some(header) => (header.header_checksum, header.global_checksum.swap_bytes()),
+        None => (0, 0),
+    };
+
+    serial.write_byte((header_checksum == expected_header) as u8);
+    serial.write_byte(header_checksum);
+    serial.write_byte((global_checksum == expected_global) as u8);
+    serial.write_byte((global_checksum >> 8) as u8);
+    serial.write_byte(global_checksum as u8);
+}
+
 #[arduino_hal::entry]
 fn main() -> ! {
     let peripherals = arduino_hal::Peripherals::take().unwrap();
@@ -133,8 +262,9 @@ fn main() -> ! {
                     num_banks = header.decode_rom_size();
                 }
 
+                let mut seq: u8 = 0;
                 for i in 0..num_banks {
-                    dump_rom_bank(i, &mut cart, &mut serial);
+                    dump_rom_bank(i, &mut cart, &mut serial, &mut seq);
                 }
             }
             Command::DumpRam => {
@@ -143,9 +273,10 @@ fn main() -> ! {
                     num_banks = header.decode_ram_size();
                 }
 
+                let mut seq: u8 = 0;
                 cart.enable_ram();
                 for i in 0..num_banks {
-                    dump_ram_bank(i, &mut cart, &mut serial);
+                    dump_ram_bank(i, &mut cart, &mut serial, &mut seq);
                 }
                 cart.disable_ram();
             }
@@ -161,6 +292,54 @@ fn main() -> ! {
                 }
                 cart.disable_ram();
             }
+            Command::FlashRom => {
+                let mut num_banks = 0;
+                if let Some(header) = cart.header.as_ref() {
+                    num_banks = header.decode_rom_size();
+                }
+
+                for i in 0..num_banks {
+                    flash_rom_bank(i, &mut cart, &mut serial);
+                }
+                cart.flash_reset();
+            }
+            Command::IdentifyFlash => {
+                let (manufacturer_id, device_id) = cart.read_flash_id();
+                serial.write_byte(manufacturer_id);
+                serial.write_byte(device_id);
+            }
+            Command::VerifyChecksums => {
+                verify_checksums(&mut cart, &mut serial);
+            }
+            Command::DumpRtc => {
+                cart.enable_ram();
+                for b in cart.read_rtc_registers() {
+                    serial.write_byte(b);
+                }
+                cart.disable_ram();
+            }
+            Command::FlashRtc => {
+                let mut values = [0u8; 5];
+                for v in &mut values {
+                    *v = serial.read_byte();
+                }
+
+                cart.enable_ram();
+                cart.write_rtc_registers(&values);
+                cart.disable_ram();
+            }
+            Command::Configure => {
+                let ubrr_hi = serial.read_byte();
+                let ubrr_lo = serial.read_byte();
+                let read_settle_us = serial.read_byte();
+                let write_pulse_ms = serial.read_byte();
+
+                set_baud_rate_divisor(((ubrr_hi as u16) << 8) | ubrr_lo as u16);
+                cart.timing = BusTiming {
+                    read_settle_us: read_settle_us as u16,
+                    write_pulse_ms: write_pulse_ms as u16,
+                };
+            }
             Command::NoOp => (),
         }
     }